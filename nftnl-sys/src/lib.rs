@@ -71,3 +71,21 @@ cfg_if::cfg_if! {
         pub use self::nftnl_1_0_6::*;
     }
 }
+
+// `NFTNL_RULE_ID` and `NFTNL_RULE_POSITION_ID` were added to libnftnl's `nftnl_rule_attr`
+// enum in 1.1.1, after the generated bindings for that version were last regenerated by
+// `generate_bindings.sh`. Declare them here rather than depending on each version module
+// above to re-export them, so `nftnl` can rely on them being present for every version
+// feature from 1.1.1 onwards.
+#[cfg(any(
+    feature = "nftnl-1-1-1",
+    feature = "nftnl-1-1-2",
+    feature = "nftnl-1-2-0"
+))]
+pub const NFTNL_RULE_ID: u32 = 8;
+#[cfg(any(
+    feature = "nftnl-1-1-1",
+    feature = "nftnl-1-1-2",
+    feature = "nftnl-1-2-0"
+))]
+pub const NFTNL_RULE_POSITION_ID: u32 = 9;
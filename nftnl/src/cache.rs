@@ -0,0 +1,116 @@
+#![cfg(feature = "query")]
+
+use crate::{chain::Chain, rule::list_rules_for_chain, rule::Rule};
+use std::cell::{Ref, RefCell};
+use std::sync::Arc;
+use tracing::error;
+
+/// An opt-in, lazily-populated cache of the rules in a [`Chain`].
+///
+/// The first call to [`rules`](RuleCache::rules) (or [`contains`](RuleCache::contains))
+/// fetches the chain's rules from the kernel via [`list_rules_for_chain`] and keeps them
+/// around for subsequent calls, avoiding a netlink round trip on every lookup.
+///
+/// Mutations that bypass the cache, such as adding or deleting rules via a batch, make it
+/// stale: callers must call [`invalidate`](RuleCache::invalidate) after any batch that
+/// touches the cached chain, or the cache will keep serving the rules as they were before
+/// that batch.
+pub struct RuleCache {
+    chain: Arc<Chain>,
+    rules: RefCell<Option<Vec<Rule>>>,
+}
+
+impl RuleCache {
+    /// Creates an empty cache for `chain`. Nothing is fetched from the kernel until the
+    /// cache is first read.
+    pub fn new(chain: Arc<Chain>) -> Self {
+        RuleCache {
+            chain,
+            rules: RefCell::new(None),
+        }
+    }
+
+    /// Returns the cached rules of this chain, populating the cache from the kernel on the
+    /// first call after creation or after [`invalidate`](RuleCache::invalidate).
+    ///
+    /// If the underlying query fails, the error is logged and an empty slice is returned for
+    /// this call, but the failure itself is not cached: the cache is left unpopulated, so the
+    /// next call retries the query instead of permanently serving the empty result.
+    pub fn rules(&self) -> Ref<'_, [Rule]> {
+        if self.rules.borrow().is_none() {
+            match list_rules_for_chain(&self.chain) {
+                Ok(rules) => *self.rules.borrow_mut() = Some(rules),
+                Err(err) => {
+                    error!("Failed to populate rule cache - {}", err);
+                    return Ref::map(self.rules.borrow(), |_| -> &[Rule] { &[] });
+                }
+            }
+        }
+        Ref::map(self.rules.borrow(), |rules| {
+            rules.as_deref().expect("populated above")
+        })
+    }
+
+    /// Returns whether a rule matching the content of `rule` is present in the cached rules,
+    /// populating the cache first if necessary.
+    ///
+    /// This compares [`Rule::content_key`], a rendering of the rule with its kernel-assigned
+    /// handle and position stripped out, rather than raw [`Rule::get_str`] (which embeds the
+    /// handle) or [`Rule`]'s [`PartialEq`] impl (which compares chain and handle directly).
+    /// Both of those would spuriously report "not found" for the exact case this method
+    /// exists for: checking whether an equivalent rule already exists before adding a
+    /// freshly-built `rule`, which has no handle yet, against cached rules fetched from the
+    /// kernel, which all do.
+    pub fn contains(&self, rule: &Rule) -> bool {
+        let needle = rule.content_key();
+        self.rules()
+            .iter()
+            .any(|cached| cached.content_key() == needle)
+    }
+
+    /// Discards the cached rules. The next call to [`rules`](RuleCache::rules) or
+    /// [`contains`](RuleCache::contains) will re-fetch them from the kernel.
+    ///
+    /// Must be called after any add/delete batch that touches this cache's chain.
+    pub fn invalidate(&self) {
+        *self.rules.borrow_mut() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::{ProtoFamily, Table};
+    use std::ffi::CString;
+
+    fn test_cache() -> RuleCache {
+        let mut table = Table::new();
+        table.set_family(ProtoFamily::Inet);
+        table.set_name(&CString::new("nftnl-rs-test-table").unwrap());
+
+        let mut chain = Chain::new(&Arc::new(table));
+        chain.set_name(&CString::new("test-chain").unwrap());
+
+        RuleCache::new(Arc::new(chain))
+    }
+
+    #[test]
+    fn invalidate_before_population_is_a_no_op() {
+        let cache = test_cache();
+        cache.invalidate();
+    }
+
+    #[test]
+    fn rules_does_not_cache_a_failed_query() {
+        let cache = test_cache();
+        // There is no reachable nf_tables kernel module in the test environment, so this
+        // query fails and `rules()` is expected to degrade to an empty slice rather than
+        // panic, without caching that failure - the next call must still retry the query
+        // rather than being stuck serving the empty result forever.
+        assert_eq!(cache.rules().len(), 0);
+        assert!(
+            cache.rules.borrow().is_none(),
+            "a failed query must not be cached, so the next call retries"
+        );
+    }
+}
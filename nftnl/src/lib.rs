@@ -0,0 +1,53 @@
+//! Safe abstraction over `libnftnl`. Provides low-level building blocks for nftables
+//! administration tools.
+//!
+//! See [`nftnl-sys`] for the low level FFI bindings this crate wraps.
+//!
+//! [`nftnl-sys`]: https://crates.io/crates/nftnl-sys
+
+macro_rules! try_alloc {
+    ($e:expr) => {{
+        let ptr = $e;
+        if ptr.is_null() {
+            panic!("oom");
+        }
+        ptr
+    }};
+}
+
+mod chain;
+pub use self::chain::Chain;
+
+#[cfg(feature = "query")]
+pub mod cache;
+#[cfg(feature = "query")]
+pub use self::cache::RuleCache;
+
+mod expr;
+pub use self::expr::Expression;
+
+#[cfg(feature = "query")]
+pub mod query;
+
+mod rule;
+pub use self::rule::{ExprRef, Rule, RuleExprIter};
+#[cfg(feature = "query")]
+pub use self::rule::{
+    get_rules_cb, get_rules_cb_for_table, list_rules_for_chain, list_rules_for_table,
+};
+
+mod table;
+pub use self::table::Table;
+
+/// The type of message to construct and send to netlink.
+pub enum MsgType {
+    /// Add a new nftables item.
+    Add,
+    /// Remove an nftables item.
+    Del,
+}
+
+/// Trait for all types in this crate that can be serialized to a netlink message.
+pub(crate) unsafe trait NlMsg {
+    unsafe fn write(&self, buf: *mut std::ffi::c_void, seq: u32, msg_type: MsgType);
+}
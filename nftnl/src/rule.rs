@@ -1,5 +1,6 @@
 use crate::{chain::Chain, expr::Expression, MsgType};
 use nftnl_sys::{self as sys, libc};
+use std::collections::HashMap;
 use std::ffi::{c_void, CStr, CString};
 use std::fmt::Debug;
 use std::os::raw::c_char;
@@ -52,14 +53,60 @@ impl Rule {
         unsafe { sys::nftnl_rule_get_u64(self.rule, sys::NFTNL_RULE_POSITION as u16) }
     }
 
-    /// Sets the position of this rule within the chain it lives in. By default a new rule is added
-    /// to the end of the chain.
+    /// Sets the position of this rule within the chain it lives in, referencing the kernel
+    /// handle of the preceding rule. By default a new rule is added to the end of the chain.
+    ///
+    /// Mutually exclusive with [`set_position_id`](Rule::set_position_id): a rule that is
+    /// being positioned relative to another rule created in the same batch (and thus has no
+    /// handle yet) must use that instead.
     pub fn set_position(&mut self, position: u64) {
         unsafe {
             sys::nftnl_rule_set_u64(self.rule, sys::NFTNL_RULE_POSITION as u16, position);
         }
     }
 
+    /// Returns the batch-local ID previously assigned via [`set_id`](Rule::set_id).
+    #[cfg(any(
+        feature = "nftnl-1-1-1",
+        feature = "nftnl-1-1-2",
+        feature = "nftnl-1-2-0"
+    ))]
+    pub fn get_id(&self) -> u32 {
+        unsafe { sys::nftnl_rule_get_u32(self.rule, sys::NFTNL_RULE_ID as u16) }
+    }
+
+    /// Tags this rule with a caller-chosen ID that is only meaningful within the netlink
+    /// batch it is sent in. Another rule added in the same batch can then use
+    /// [`set_position_id`](Rule::set_position_id) with this ID to request to be inserted
+    /// before/after this rule, without needing its kernel handle, which does not exist yet
+    /// for a rule that is still being created.
+    #[cfg(any(
+        feature = "nftnl-1-1-1",
+        feature = "nftnl-1-1-2",
+        feature = "nftnl-1-2-0"
+    ))]
+    pub fn set_id(&mut self, id: u32) {
+        unsafe {
+            sys::nftnl_rule_set_u32(self.rule, sys::NFTNL_RULE_ID as u16, id);
+        }
+    }
+
+    /// Positions this rule relative to another rule added in the same batch, referencing
+    /// that rule's [`set_id`](Rule::set_id) instead of a real kernel handle.
+    ///
+    /// Mutually exclusive with [`set_position`](Rule::set_position): a rule must not set
+    /// both its position and its position ID.
+    #[cfg(any(
+        feature = "nftnl-1-1-1",
+        feature = "nftnl-1-1-2",
+        feature = "nftnl-1-2-0"
+    ))]
+    pub fn set_position_id(&mut self, id: u32) {
+        unsafe {
+            sys::nftnl_rule_set_u32(self.rule, sys::NFTNL_RULE_POSITION_ID as u16, id);
+        }
+    }
+
     pub fn get_handle(&self) -> u64 {
         unsafe { sys::nftnl_rule_get_u64(self.rule, sys::NFTNL_RULE_HANDLE as u16) }
     }
@@ -77,6 +124,21 @@ impl Rule {
         unsafe { sys::nftnl_rule_add_expr(self.rule, expr.to_expr(self)) }
     }
 
+    /// Returns an iterator over the expressions contained in this rule, in evaluation order.
+    ///
+    /// This is mainly useful for rules obtained from the kernel (e.g. via
+    /// [`list_rules_for_chain`]), where the expressions are otherwise only visible as part of
+    /// the textual description returned by [`get_str`](Rule::get_str).
+    pub fn exprs(&self) -> RuleExprIter<'_> {
+        unsafe {
+            let iter = try_alloc!(sys::nftnl_expr_iter_create(self.rule));
+            RuleExprIter {
+                iter,
+                _marker: std::marker::PhantomData,
+            }
+        }
+    }
+
     /// Returns a reference to the [`Chain`] this rule lives in.
     ///
     /// [`Chain`]: struct.Chain.html
@@ -117,6 +179,55 @@ impl Rule {
         }
     }
 
+    /// Returns a textual description of this rule with the kernel-assigned handle and
+    /// position stripped out, so two rules with identical expressions and userdata but
+    /// different handles (e.g. a freshly-built rule and its kernel-fetched counterpart)
+    /// compare equal.
+    pub(crate) fn content_key(&self) -> CString {
+        unsafe {
+            let mut buf = vec![0u8; sys::nft_nlmsg_maxsize() as usize];
+            let header = sys::nftnl_nlmsg_build_hdr(
+                buf.as_mut_ptr() as *mut c_char,
+                libc::NFT_MSG_NEWRULE as u16,
+                self.chain.get_table().get_family() as u16,
+                0,
+                0,
+            );
+            sys::nftnl_rule_nlmsg_build_payload(header, self.rule);
+
+            let tmp = try_alloc!(sys::nftnl_rule_alloc());
+            let err = sys::nftnl_rule_nlmsg_parse(header, tmp);
+            if err < 0 {
+                sys::nftnl_rule_free(tmp);
+                panic!("Failed to compute rule content key - {}", err);
+            }
+
+            sys::nftnl_rule_unset(tmp, sys::NFTNL_RULE_HANDLE as u16);
+            sys::nftnl_rule_unset(tmp, sys::NFTNL_RULE_POSITION as u16);
+            #[cfg(any(
+                feature = "nftnl-1-1-1",
+                feature = "nftnl-1-1-2",
+                feature = "nftnl-1-2-0"
+            ))]
+            {
+                sys::nftnl_rule_unset(tmp, sys::NFTNL_RULE_ID as u16);
+                sys::nftnl_rule_unset(tmp, sys::NFTNL_RULE_POSITION_ID as u16);
+            }
+
+            let mut descr_buf = vec![0i8; 4096];
+            sys::nftnl_rule_snprintf(
+                descr_buf.as_mut_ptr(),
+                (descr_buf.len() - 1) as u64,
+                tmp,
+                sys::NFTNL_OUTPUT_DEFAULT,
+                0,
+            );
+            let key = CStr::from_ptr(descr_buf.as_ptr()).to_owned();
+            sys::nftnl_rule_free(tmp);
+            key
+        }
+    }
+
     /// Returns the raw handle.
     pub fn as_ptr(&self) -> *const sys::nftnl_rule {
         self.rule as *const sys::nftnl_rule
@@ -126,6 +237,60 @@ impl Rule {
     pub fn as_mut_ptr(&mut self) -> *mut sys::nftnl_rule {
         self.rule
     }
+
+    /// Creates an independent copy of this rule, targeting `chain` instead of the chain it
+    /// currently lives in.
+    ///
+    /// The clone gets its own `nftnl_rule` allocation: this rule's attributes and expressions
+    /// are serialized and re-parsed into it, rather than shared. `NFTNL_RULE_TABLE`,
+    /// `NFTNL_RULE_CHAIN` and `NFTNL_RULE_FAMILY` are updated to match `chain`, and the stale
+    /// `NFTNL_RULE_HANDLE` is cleared so the clone is treated as a brand new rule when added,
+    /// rather than a modification of the rule it was duplicated from. Pass
+    /// `self.get_chain()` to duplicate the rule within the same chain.
+    pub fn duplicate(&self, chain: Arc<Chain>) -> Rule {
+        unsafe {
+            // `nftnl_rule_nlmsg_build_payload` does no bounds checking against the buffer it
+            // is handed, so the buffer must be at least as large as a netlink message can
+            // get. `nft_nlmsg_maxsize()` is the same bound libnftnl itself uses when sizing
+            // the buffers it builds batches into, so a single rule's serialized form, which
+            // is always smaller than a whole batch, is guaranteed to fit.
+            let mut buf = vec![0u8; sys::nft_nlmsg_maxsize() as usize];
+            let header = sys::nftnl_nlmsg_build_hdr(
+                buf.as_mut_ptr() as *mut c_char,
+                libc::NFT_MSG_NEWRULE as u16,
+                self.chain.get_table().get_family() as u16,
+                0,
+                0,
+            );
+            sys::nftnl_rule_nlmsg_build_payload(header, self.rule);
+
+            let new_rule = try_alloc!(sys::nftnl_rule_alloc());
+            let err = sys::nftnl_rule_nlmsg_parse(header, new_rule);
+            if err < 0 {
+                sys::nftnl_rule_free(new_rule);
+                panic!("Failed to duplicate rule - {}", err);
+            }
+
+            sys::nftnl_rule_set_u32(
+                new_rule,
+                sys::NFTNL_RULE_FAMILY as u16,
+                chain.get_table().get_family() as u32,
+            );
+            sys::nftnl_rule_set_str(
+                new_rule,
+                sys::NFTNL_RULE_TABLE as u16,
+                chain.get_table().get_name().as_ptr(),
+            );
+            sys::nftnl_rule_set_str(
+                new_rule,
+                sys::NFTNL_RULE_CHAIN as u16,
+                chain.get_name().as_ptr(),
+            );
+            sys::nftnl_rule_unset(new_rule, sys::NFTNL_RULE_HANDLE as u16);
+
+            Rule::from_raw(new_rule, chain)
+        }
+    }
 }
 
 impl Debug for Rule {
@@ -134,6 +299,68 @@ impl Debug for Rule {
     }
 }
 
+/// Iterator over the expressions contained in a [`Rule`], created by [`Rule::exprs`].
+///
+/// Borrows the `Rule` so the underlying `nftnl_rule` it walks outlives it. The expressions
+/// yielded are owned by the rule and must not be freed through this iterator.
+pub struct RuleExprIter<'a> {
+    iter: *mut sys::nftnl_expr_iter,
+    _marker: std::marker::PhantomData<&'a Rule>,
+}
+
+impl<'a> Iterator for RuleExprIter<'a> {
+    type Item = ExprRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            let expr = sys::nftnl_expr_iter_next(self.iter);
+            if expr.is_null() {
+                None
+            } else {
+                Some(ExprRef {
+                    expr,
+                    _marker: std::marker::PhantomData,
+                })
+            }
+        }
+    }
+}
+
+impl<'a> Drop for RuleExprIter<'a> {
+    fn drop(&mut self) {
+        unsafe { sys::nftnl_expr_iter_destroy(self.iter) };
+    }
+}
+
+/// A single expression borrowed from a [`Rule`] via [`RuleExprIter`].
+///
+/// The expression is owned by the rule it came from; this is just a read-only view of it.
+pub struct ExprRef<'a> {
+    expr: *mut sys::nftnl_expr,
+    _marker: std::marker::PhantomData<&'a Rule>,
+}
+
+impl<'a> ExprRef<'a> {
+    /// Returns the name of this expression's type, e.g. `"meta"`, `"payload"` or `"immediate"`.
+    pub fn get_name(&self) -> CString {
+        unsafe {
+            let ptr = sys::nftnl_expr_get_str(self.expr, sys::NFTNL_EXPR_NAME as u16);
+            CStr::from_ptr(ptr).to_owned()
+        }
+    }
+
+    /// Returns the raw handle of this expression.
+    pub fn as_ptr(&self) -> *const sys::nftnl_expr {
+        self.expr as *const sys::nftnl_expr
+    }
+}
+
+impl<'a> Debug for ExprRef<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.get_name())
+    }
+}
+
 impl PartialEq for Rule {
     fn eq(&self, other: &Self) -> bool {
         self.get_chain() == other.get_chain() && self.get_handle() == other.get_handle()
@@ -225,3 +452,176 @@ pub fn list_rules_for_chain(chain: &Arc<Chain>) -> Result<Vec<Rule>, crate::quer
         }),
     )
 }
+
+#[cfg(feature = "query")]
+pub fn get_rules_cb_for_table(
+    header: &libc::nlmsghdr,
+    ((table, chains), rules): &mut (
+        (&Arc<crate::table::Table>, &mut HashMap<CString, Arc<Chain>>),
+        &mut Vec<Rule>,
+    ),
+) -> libc::c_int {
+    unsafe {
+        let rule = sys::nftnl_rule_alloc();
+        if rule == std::ptr::null_mut() {
+            return mnl::mnl_sys::MNL_CB_ERROR;
+        }
+        let err = sys::nftnl_rule_nlmsg_parse(header, rule);
+        if err < 0 {
+            error!("Failed to parse nelink rule message - {}", err);
+            sys::nftnl_rule_free(rule);
+            return err;
+        }
+
+        let chain_name_ptr = sys::nftnl_rule_get_str(rule, sys::NFTNL_RULE_CHAIN as u16);
+        if chain_name_ptr == std::ptr::null() {
+            error!("Rule in table dump is missing its chain name");
+            sys::nftnl_rule_free(rule);
+            return mnl::mnl_sys::MNL_CB_ERROR;
+        }
+        let chain_name = CStr::from_ptr(chain_name_ptr).to_owned();
+
+        let chain = match chains.get(&chain_name) {
+            Some(chain) => chain.clone(),
+            None => {
+                let mut chain = Chain::new(table);
+                chain.set_name(&chain_name);
+                let chain = Arc::new(chain);
+                chains.insert(chain_name, chain.clone());
+                chain
+            }
+        };
+
+        rules.push(Rule::from_raw(rule, chain));
+    }
+    mnl::mnl_sys::MNL_CB_OK
+}
+
+/// Lists every rule in `table`, across all of its chains, in a single netlink dump.
+///
+/// This is cheaper than calling [`list_rules_for_chain`] once per chain, since the kernel is
+/// only asked to filter by `NFTNL_RULE_TABLE`/`NFTNL_RULE_FAMILY` and not by chain. Each
+/// returned [`Rule`] carries the [`Chain`] its `NFTNL_RULE_CHAIN` attribute names; chains are
+/// looked up by name as rules are parsed and reused across rules of the same chain, rather
+/// than being fetched from the kernel themselves.
+#[cfg(feature = "query")]
+pub fn list_rules_for_table(
+    table: &Arc<crate::table::Table>,
+) -> Result<Vec<Rule>, crate::query::Error> {
+    let mut chains: HashMap<CString, Arc<Chain>> = HashMap::new();
+    crate::query::list_objects_with_data(
+        libc::NFT_MSG_GETRULE as u16,
+        get_rules_cb_for_table,
+        (table, &mut chains),
+        // only retrieve rules from the currently targetted table, across all its chains
+        Some(&|hdr| unsafe {
+            let rule = sys::nftnl_rule_alloc();
+            if rule as *const _ == std::ptr::null() {
+                return Err(crate::query::Error::NetlinkAllocationFailed);
+            }
+
+            sys::nftnl_rule_set_str(
+                rule,
+                sys::NFTNL_RULE_TABLE as u16,
+                table.get_name().as_ptr(),
+            );
+            sys::nftnl_rule_set_u32(
+                rule,
+                sys::NFTNL_RULE_FAMILY as u16,
+                table.get_family() as u32,
+            );
+
+            sys::nftnl_rule_nlmsg_build_payload(hdr, rule);
+
+            sys::nftnl_rule_free(rule);
+            Ok(())
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::{ProtoFamily, Table};
+
+    fn test_chain(name: &str) -> Arc<Chain> {
+        let mut table = Table::new();
+        table.set_family(ProtoFamily::Inet);
+        table.set_name(&CString::new("nftnl-rs-test-table").unwrap());
+
+        let mut chain = Chain::new(&Arc::new(table));
+        chain.set_name(&CString::new(name).unwrap());
+        Arc::new(chain)
+    }
+
+    #[test]
+    fn new_rule_has_no_exprs() {
+        let rule = Rule::new(test_chain("test-chain"));
+        assert_eq!(rule.exprs().count(), 0);
+    }
+
+    #[test]
+    #[cfg(any(
+        feature = "nftnl-1-1-1",
+        feature = "nftnl-1-1-2",
+        feature = "nftnl-1-2-0"
+    ))]
+    fn rule_id_roundtrips() {
+        let mut rule = Rule::new(test_chain("test-chain"));
+        rule.set_id(7);
+        assert_eq!(rule.get_id(), 7);
+    }
+
+    #[test]
+    fn duplicate_clears_handle_and_retargets_chain() {
+        let source_chain = test_chain("source-chain");
+        let mut rule = Rule::new(source_chain);
+        rule.set_handle(42);
+        assert_eq!(rule.get_handle(), 42);
+
+        let target_chain = test_chain("target-chain");
+        let clone = rule.duplicate(target_chain.clone());
+
+        assert_eq!(clone.get_handle(), 0);
+        assert_eq!(clone.get_chain().get_name(), target_chain.get_name());
+    }
+
+    #[test]
+    #[cfg(feature = "query")]
+    fn get_rules_cb_for_table_resolves_and_caches_chain_by_name() {
+        let mut table = Table::new();
+        table.set_family(ProtoFamily::Inet);
+        table.set_name(&CString::new("nftnl-rs-test-table").unwrap());
+        let table = Arc::new(table);
+
+        let chain = {
+            let mut chain = Chain::new(&table);
+            chain.set_name(&CString::new("shared-chain").unwrap());
+            Arc::new(chain)
+        };
+        let rule_a = Rule::new(chain.clone());
+        let rule_b = Rule::new(chain.clone());
+
+        let mut chains: HashMap<CString, Arc<Chain>> = HashMap::new();
+        let mut rules: Vec<Rule> = Vec::new();
+        for rule in [&rule_a, &rule_b] {
+            unsafe {
+                let mut buf = vec![0u8; sys::nft_nlmsg_maxsize() as usize];
+                let header = sys::nftnl_nlmsg_build_hdr(
+                    buf.as_mut_ptr() as *mut c_char,
+                    libc::NFT_MSG_NEWRULE as u16,
+                    table.get_family() as u16,
+                    0,
+                    0,
+                );
+                sys::nftnl_rule_nlmsg_build_payload(header, rule.rule);
+                let header = &*(header as *const libc::nlmsghdr);
+                get_rules_cb_for_table(header, &mut ((&table, &mut chains), &mut rules));
+            }
+        }
+
+        assert_eq!(rules.len(), 2);
+        assert_eq!(chains.len(), 1);
+        assert!(Arc::ptr_eq(&rules[0].get_chain(), &rules[1].get_chain()));
+    }
+}